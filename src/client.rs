@@ -0,0 +1,63 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! The client half of this crate: consuming a CDP server rather than
+//! implementing one.
+
+use futures::Stream;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::error::CdpError;
+use crate::traits::{DeserializeCdpEvent, SerializeCdpCommand};
+
+/// An encoded event pushed by the server, analogous to `CdpServerCommand` on
+/// the server side: its name can be inspected before attempting to
+/// deserialize it into a concrete event type.
+pub trait CdpClientEvent: Sized {
+    /// The type of a deserialization error.
+    type Error: Error;
+
+    /// Get the name of the encoded event.
+    fn event_name(&self) -> &str;
+
+    /// Attempt to deserialize the encoded event into a specific event type.
+    ///
+    /// If the event type recognizes the name of this event and attempts to
+    /// deserialize it, returns the result of that deserialization wrapped in
+    /// an Ok. Otherwise, gives back the encoded event wrapped in an Err.
+    fn deserialize_event<E>(self) -> Result<Result<E, Self::Error>, Self>
+    where
+        E: for<'de> DeserializeCdpEvent<'de>;
+}
+
+/// A cloneable handle to a CDP server, able to send commands and receive
+/// events over whatever transport it was constructed with.
+///
+/// Cloning a `CdpClient` reuses the one underlying connection; dropping the
+/// last clone closes it.
+pub trait CdpClient: Sized + Clone {
+    /// The encoded event type produced by this client's transport.
+    type Event: CdpClientEvent;
+
+    /// The type of a stream of encoded events; see `events`.
+    type EventStream: Stream<Item = Self::Event>;
+
+    /// Send a command to the server and await its response.
+    ///
+    /// The returned future resolves once a response tagged with this
+    /// command's request id comes back from the server, correlating it with
+    /// the command that was sent the way every CDP client must.
+    fn command<C>(
+        &self,
+        cmd: &C,
+    ) -> Pin<Box<dyn Future<Output = Result<C::Response, CdpError>> + Send>>
+    where
+        C: SerializeCdpCommand;
+
+    /// A stream of every event pushed by the server on this connection,
+    /// regardless of which session it is scoped to.
+    fn events(&self) -> Self::EventStream;
+}
@@ -0,0 +1,109 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde_json::Value;
+use std::borrow::Cow;
+use std::error;
+use std::fmt;
+
+/// The canonical code for a command whose name wasn't recognized.
+pub const METHOD_NOT_FOUND: i32 = -32601;
+/// The canonical code for a command whose parameters were invalid.
+pub const INVALID_PARAMS: i32 = -32602;
+/// The canonical code for an internal, server implementation-specific error.
+pub const INTERNAL_ERROR: i32 = -32603;
+/// The canonical code for a recognized, well-formed command that could not
+/// be carried out.
+pub const SERVER_ERROR: i32 = -32000;
+/// The code for a command rejected by the flow-control layer; see
+/// `CdpServerResponder::throttled`.
+pub const THROTTLED: i32 = -32001;
+/// The code for a command whose deadline elapsed before it was answered;
+/// see `CdpServerResponder::deadline_exceeded`.
+pub const DEADLINE_EXCEEDED: i32 = -32002;
+
+/// A structured CDP error object: an integer `code`, a human-readable
+/// `message`, and optional machine-readable `data`.
+///
+/// This lets a client branch on `code` instead of parsing `message`, and
+/// lets a server attach structured context (the offending parameter path, a
+/// retryable flag, ...) via `data`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CdpError {
+    pub code: i32,
+    pub message: Cow<'static, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl CdpError {
+    /// Build an error with the given code and message and no `data`.
+    pub fn new<M>(code: i32, message: M) -> Self
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        CdpError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// Attach machine-readable `data` to this error.
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// The canonical error for a command whose name wasn't recognized.
+    pub fn method_not_found() -> Self {
+        CdpError::new(METHOD_NOT_FOUND, "Method not found")
+    }
+
+    /// The canonical error for a command whose parameters were invalid.
+    pub fn invalid_params<M>(message: M) -> Self
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        CdpError::new(INVALID_PARAMS, message)
+    }
+
+    /// The canonical error for a recognized, well-formed command that could
+    /// not be carried out.
+    pub fn server_error<M>(message: M) -> Self
+    where
+        M: Into<Cow<'static, str>>,
+    {
+        CdpError::new(SERVER_ERROR, message)
+    }
+
+    /// The canonical error for an internal, server implementation-specific
+    /// error.
+    pub fn internal_error() -> Self {
+        CdpError::new(INTERNAL_ERROR, "Internal error")
+    }
+
+    /// The error for a command rejected by the flow-control layer.
+    pub fn throttled() -> Self {
+        CdpError::new(THROTTLED, "Too many outstanding commands")
+    }
+
+    /// The error for a command whose deadline elapsed before it was
+    /// answered.
+    pub fn deadline_exceeded() -> Self {
+        CdpError::new(DEADLINE_EXCEEDED, "Deadline exceeded")
+    }
+}
+
+impl fmt::Display for CdpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (code {})", self.message, self.code)
+    }
+}
+
+impl error::Error for CdpError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
@@ -0,0 +1,232 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A compact, length-prefixed binary framing for transports that aren't
+//! WebSocket/JSON-over-HTTP, e.g. raw pipes or sockets.
+//!
+//! Each frame is `[version: u8][length: u32 big-endian][payload: length
+//! bytes]`. The version byte's low nibble is the format version; its high
+//! nibble is a mandatory feature mask for future, incompatible extensions of
+//! this same version. A reader that doesn't recognize a set feature bit must
+//! reject the frame rather than guess at its meaning.
+
+use serde_json;
+use std::error;
+use std::fmt;
+use std::str;
+
+use crate::server::CdpServerCommand;
+use crate::traits::DeserializeCdpCommand;
+
+/// The version byte written by `encode_with_len` and understood by
+/// `decode_frame`.
+pub const VERSION: u8 = 0x01;
+
+/// The number of header bytes preceding the payload: one version byte plus
+/// a 4-byte big-endian length.
+const HEADER_LEN: usize = 5;
+
+/// Why a frame could not be decoded.
+#[derive(Debug, PartialEq, Eq)]
+pub enum FrameDecodeError {
+    /// The version byte's mandatory-feature bits include one this reader
+    /// doesn't support, carrying the unsupported bit mask.
+    UnknownMandatoryFeature(u8),
+    /// The version byte doesn't match any version this reader understands.
+    UnknownVersion(u8),
+    /// Fewer bytes were available than the header or the declared length
+    /// requires.
+    ShortRead,
+    /// More bytes were available after the frame than `decode_frame_exact`
+    /// expects for a single frame.
+    TrailingBytes,
+    /// The payload was supposed to be UTF-8 text but wasn't. Also returned
+    /// by `FramedCommand::decode` when the text isn't a JSON `MethodCall`.
+    BadUtf8,
+}
+
+impl fmt::Display for FrameDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameDecodeError::UnknownMandatoryFeature(bits) => {
+                write!(f, "frame requires unsupported feature bits: {:#x}", bits)
+            }
+            FrameDecodeError::UnknownVersion(version) => {
+                write!(f, "unsupported frame version: {}", version)
+            }
+            FrameDecodeError::ShortRead => write!(f, "not enough bytes for a complete frame"),
+            FrameDecodeError::TrailingBytes => write!(f, "unexpected bytes after the frame"),
+            FrameDecodeError::BadUtf8 => write!(f, "frame payload was not valid UTF-8 JSON"),
+        }
+    }
+}
+
+impl error::Error for FrameDecodeError {
+    fn description(&self) -> &str {
+        "failed to decode frame"
+    }
+}
+
+/// The low nibble of the version byte: the format version itself.
+const VERSION_MASK: u8 = 0x0f;
+/// The high nibble of the version byte: mandatory feature bits. This reader
+/// understands none of them yet, so any set bit must be rejected.
+const FEATURE_MASK: u8 = 0xf0;
+
+/// Decode one frame from the front of `bytes`.
+///
+/// On success, returns the decoded payload along with the number of bytes
+/// consumed from the front of `bytes`, so the caller can keep decoding
+/// subsequent frames from the same buffer.
+pub fn decode_frame(bytes: &[u8]) -> Result<(&[u8], usize), FrameDecodeError> {
+    if bytes.len() < HEADER_LEN {
+        return Err(FrameDecodeError::ShortRead);
+    }
+    let version_byte = bytes[0];
+    let features = version_byte & FEATURE_MASK;
+    if features != 0 {
+        return Err(FrameDecodeError::UnknownMandatoryFeature(features));
+    }
+    if version_byte & VERSION_MASK != VERSION {
+        return Err(FrameDecodeError::UnknownVersion(version_byte));
+    }
+    let len = ((bytes[1] as usize) << 24)
+        | ((bytes[2] as usize) << 16)
+        | ((bytes[3] as usize) << 8)
+        | (bytes[4] as usize);
+    let end = HEADER_LEN
+        .checked_add(len)
+        .ok_or(FrameDecodeError::ShortRead)?;
+    if bytes.len() < end {
+        return Err(FrameDecodeError::ShortRead);
+    }
+    Ok((&bytes[HEADER_LEN..end], end))
+}
+
+/// Decode exactly one frame that is expected to fill the whole buffer,
+/// rejecting any trailing bytes.
+pub fn decode_frame_exact(bytes: &[u8]) -> Result<&[u8], FrameDecodeError> {
+    let (payload, consumed) = decode_frame(bytes)?;
+    if consumed != bytes.len() {
+        return Err(FrameDecodeError::TrailingBytes);
+    }
+    Ok(payload)
+}
+
+/// Encode `payload` as a single frame and append it to `out`.
+pub fn encode_with_len(payload: &[u8], out: &mut Vec<u8>) {
+    out.push(VERSION);
+    out.push((payload.len() >> 24) as u8);
+    out.push((payload.len() >> 16) as u8);
+    out.push((payload.len() >> 8) as u8);
+    out.push(payload.len() as u8);
+    out.extend_from_slice(payload);
+}
+
+/// A command decoded from a single frame's payload, which is itself the
+/// usual JSON `MethodCall` object (`{"id", "method", "params", "sessionId"}`)
+/// used by the rest of this crate.
+pub struct FramedCommand {
+    id: u64,
+    method: String,
+    session_id: Option<String>,
+    params: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct MethodCall {
+    id: u64,
+    method: String,
+    #[serde(rename = "sessionId")]
+    session_id: Option<String>,
+    #[serde(default)]
+    params: serde_json::Value,
+}
+
+impl FramedCommand {
+    /// Decode a single frame's payload bytes, which must be UTF-8 encoded
+    /// JSON, into a `FramedCommand`.
+    pub fn decode(payload: &[u8]) -> Result<Self, FrameDecodeError> {
+        let text = str::from_utf8(payload).map_err(|_| FrameDecodeError::BadUtf8)?;
+        let call: MethodCall =
+            serde_json::from_str(text).map_err(|_| FrameDecodeError::BadUtf8)?;
+        Ok(FramedCommand {
+            id: call.id,
+            method: call.method,
+            session_id: call.session_id,
+            params: call.params,
+        })
+    }
+
+    /// The `id` of the `MethodCall` this command was decoded from.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl CdpServerCommand for FramedCommand {
+    type Error = serde_json::Error;
+
+    fn command_name(&self) -> &str {
+        &self.method
+    }
+
+    fn session_id(&self) -> Option<&str> {
+        self.session_id.as_ref().map(String::as_str)
+    }
+
+    fn deadline(&self) -> Option<::std::time::Instant> {
+        None
+    }
+
+    fn deserialize_command<C>(self) -> Result<Result<C, Self::Error>, Self>
+    where
+        C: for<'de> DeserializeCdpCommand<'de>,
+    {
+        if self.method != C::NAME {
+            return Err(self);
+        }
+        let result = serde_json::from_value(self.params.clone());
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_frame() {
+        let mut buf = Vec::new();
+        encode_with_len(b"hello", &mut buf);
+        assert_eq!(decode_frame_exact(&buf).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn rejects_a_short_read() {
+        let mut buf = Vec::new();
+        encode_with_len(b"hello", &mut buf);
+        buf.truncate(buf.len() - 1);
+        assert_eq!(decode_frame(&buf), Err(FrameDecodeError::ShortRead));
+    }
+
+    #[test]
+    fn rejects_an_unknown_mandatory_feature() {
+        let mut buf = Vec::new();
+        encode_with_len(b"hello", &mut buf);
+        buf[0] |= 0x80;
+        match decode_frame(&buf) {
+            Err(FrameDecodeError::UnknownMandatoryFeature(0x80)) => {}
+            other => panic!("expected UnknownMandatoryFeature(0x80), got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_bytes() {
+        let mut buf = Vec::new();
+        encode_with_len(b"hello", &mut buf);
+        buf.push(0);
+        assert_eq!(decode_frame_exact(&buf), Err(FrameDecodeError::TrailingBytes));
+    }
+}
@@ -0,0 +1,28 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Transport-agnostic traits for implementing the Chrome DevTools Protocol
+//! (CDP), on either the server or the client side of the wire.
+
+extern crate futures;
+extern crate hmac;
+extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate sha2;
+extern crate tungstenite;
+extern crate url;
+
+pub mod client;
+pub mod error;
+pub mod framing;
+pub mod loopback;
+pub mod server;
+pub mod session;
+pub mod stdio;
+pub mod throttle;
+pub mod traits;
+pub mod ws;
@@ -0,0 +1,364 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! An in-process implementation of the server traits, backed by `mpsc`
+//! channels instead of a socket.
+//!
+//! Because the client and the server live in the same process, a command
+//! can be handed over as an already-typed value instead of bytes that need
+//! parsing, and `LoopbackCommand::deserialize_command` becomes a downcast
+//! rather than a deserialize. Responses and events still go through
+//! `serde_json::Value`, since `CdpServerResponder::respond` and
+//! `CdpServerEventSender::send_event` are only bounded by `Serialize`, but no
+//! socket or byte framing is ever involved.
+
+use serde::Serialize;
+use serde_json;
+use std::any::Any;
+use std::error;
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::error::CdpError;
+use crate::server::{CdpServerCommand, CdpServerEventSender, CdpServerResponder};
+use crate::traits::{DeserializeCdpCommand, SerializeCdpEvent};
+
+/// The error type for every fallible operation in this module: the peer end
+/// of the loopback channel has gone away. It is also returned when a
+/// response or event fails to serialize to a `serde_json::Value`.
+#[derive(Debug)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the loopback peer has disconnected")
+    }
+}
+
+impl error::Error for Disconnected {
+    fn description(&self) -> &str {
+        "the loopback peer has disconnected"
+    }
+}
+
+/// A response frame sent back over a loopback connection.
+#[derive(Debug)]
+pub struct LoopbackResponse {
+    /// The session id echoed from the command this responds to.
+    pub session_id: Option<String>,
+    /// The payload of the response.
+    pub payload: LoopbackResponsePayload,
+}
+
+/// The payload of a `LoopbackResponse`.
+#[derive(Debug)]
+pub enum LoopbackResponsePayload {
+    Success(serde_json::Value),
+    Error(CdpError),
+}
+
+/// An event frame sent to every client subscribed to a loopback connection.
+#[derive(Debug, Clone)]
+pub struct LoopbackEvent {
+    /// The session id this event is scoped to, if any.
+    pub session_id: Option<String>,
+    /// The serialized event payload.
+    pub payload: serde_json::Value,
+}
+
+/// A command handed to a handler by an in-process server, wrapping an
+/// already-typed command value instead of bytes to be parsed.
+pub struct LoopbackCommand {
+    name: String,
+    session_id: Option<String>,
+    deadline: Option<Instant>,
+    inner: Box<Any + Send>,
+    responder: Option<mpsc::Sender<LoopbackResponse>>,
+}
+
+impl LoopbackCommand {
+    /// Wrap an already-typed command, pairing it with a freshly created
+    /// responder and the receiver the response will arrive on.
+    pub fn new<C>(
+        name: &str,
+        command: C,
+        session_id: Option<String>,
+        deadline: Option<Instant>,
+    ) -> (Self, mpsc::Receiver<LoopbackResponse>)
+    where
+        C: Any + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let cmd = LoopbackCommand {
+            name: name.to_owned(),
+            session_id,
+            deadline,
+            inner: Box::new(command),
+            responder: Some(tx),
+        };
+        (cmd, rx)
+    }
+
+    /// Take the responder paired with this command, for dispatching it
+    /// separately from the typed command value.
+    pub fn take_responder(&mut self) -> LoopbackResponder {
+        LoopbackResponder {
+            session_id: self.session_id.clone(),
+            sender: self.responder.take(),
+        }
+    }
+}
+
+impl CdpServerCommand for LoopbackCommand {
+    type Error = Disconnected;
+
+    fn command_name(&self) -> &str {
+        &self.name
+    }
+
+    fn session_id(&self) -> Option<&str> {
+        self.session_id.as_ref().map(String::as_str)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn deserialize_command<C>(self) -> Result<Result<C, Self::Error>, Self>
+    where
+        C: for<'de> DeserializeCdpCommand<'de>,
+    {
+        let LoopbackCommand {
+            name,
+            session_id,
+            deadline,
+            inner,
+            responder,
+        } = self;
+        match inner.downcast::<C>() {
+            Ok(command) => Ok(Ok(*command)),
+            Err(inner) => Err(LoopbackCommand {
+                name,
+                session_id,
+                deadline,
+                inner,
+                responder,
+            }),
+        }
+    }
+}
+
+/// The responder half of a `LoopbackCommand`, returned from
+/// `LoopbackCommand::take_responder`.
+///
+/// Dropping this without calling a response method sends back
+/// `LoopbackResponsePayload::Error` carrying `CdpError::internal_error()`,
+/// matching the contract of `CdpServerResponder`.
+pub struct LoopbackResponder {
+    session_id: Option<String>,
+    sender: Option<mpsc::Sender<LoopbackResponse>>,
+}
+
+impl LoopbackResponder {
+    fn dispatch(&mut self, payload: LoopbackResponsePayload) -> Result<(), Disconnected> {
+        let sender = self.sender.take().expect(
+            "CdpServerResponder contract violated: dispatched more than once",
+        );
+        sender
+            .send(LoopbackResponse {
+                session_id: self.session_id.clone(),
+                payload,
+            })
+            .map_err(|_| Disconnected)
+    }
+}
+
+impl Drop for LoopbackResponder {
+    fn drop(&mut self) {
+        if self.sender.is_some() {
+            let _ = self.dispatch(LoopbackResponsePayload::Error(CdpError::internal_error()));
+        }
+    }
+}
+
+impl CdpServerResponder for LoopbackResponder {
+    type Error = Disconnected;
+
+    fn respond<R>(mut self, response: &R) -> Result<(), Self::Error>
+    where
+        R: Serialize,
+    {
+        let value = serde_json::to_value(response).map_err(|_| Disconnected)?;
+        self.dispatch(LoopbackResponsePayload::Success(value))
+    }
+
+    fn respond_error(mut self, error: CdpError) -> Result<(), Self::Error> {
+        self.dispatch(LoopbackResponsePayload::Error(error))
+    }
+}
+
+/// A cloneable handle which broadcasts events to every in-process client
+/// currently subscribed to it.
+///
+/// Dropping the last clone of a `LoopbackEventSender` drops every
+/// subscriber's sending half too, which closes each subscriber's receiver.
+#[derive(Clone)]
+pub struct LoopbackEventSender {
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<LoopbackEvent>>>>,
+}
+
+impl LoopbackEventSender {
+    /// Create an event sender with no subscribers yet.
+    pub fn new() -> Self {
+        LoopbackEventSender {
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Subscribe a new in-process client, returning the receiver it should
+    /// read events from.
+    pub fn subscribe(&self) -> mpsc::Receiver<LoopbackEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+impl Default for LoopbackEventSender {
+    fn default() -> Self {
+        LoopbackEventSender::new()
+    }
+}
+
+impl CdpServerEventSender for LoopbackEventSender {
+    type Error = Disconnected;
+
+    fn send_event<E>(&self, event: &E) -> Result<(), Self::Error>
+    where
+        E: SerializeCdpEvent,
+    {
+        self.send_event_to_session(None, event)
+    }
+
+    fn send_event_to_session<E>(
+        &self,
+        session_id: Option<&str>,
+        event: &E,
+    ) -> Result<(), Self::Error>
+    where
+        E: SerializeCdpEvent,
+    {
+        let value = serde_json::to_value(event).map_err(|_| Disconnected)?;
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|sender| {
+            sender
+                .send(LoopbackEvent {
+                    session_id: session_id.map(str::to_owned),
+                    payload: value.clone(),
+                })
+                .is_ok()
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq, Deserialize)]
+    struct Navigate {
+        url: String,
+    }
+
+    impl<'de> DeserializeCdpCommand<'de> for Navigate {
+        type Response = ();
+
+        const NAME: &'static str = "Page.navigate";
+    }
+
+    struct DummyEvent;
+
+    impl SerializeCdpEvent for DummyEvent {
+        const NAME: &'static str = "Page.loadEventFired";
+    }
+
+    impl Serialize for DummyEvent {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: serde::Serializer,
+        {
+            serializer.serialize_unit()
+        }
+    }
+
+    #[test]
+    fn round_trips_a_command_and_its_response() {
+        let command = Navigate {
+            url: "https://example.com".to_owned(),
+        };
+        let (mut loopback_command, rx) = LoopbackCommand::new(
+            Navigate::NAME,
+            command,
+            Some("session-1".to_owned()),
+            None,
+        );
+        let responder = loopback_command.take_responder();
+
+        let decoded = match loopback_command.deserialize_command::<Navigate>() {
+            Ok(Ok(command)) => command,
+            Ok(Err(_)) => panic!("downcast to the exact command type can't fail to deserialize"),
+            Err(_) => panic!("command should downcast to the type it was constructed with"),
+        };
+        assert_eq!(
+            decoded,
+            Navigate {
+                url: "https://example.com".to_owned(),
+            }
+        );
+
+        responder.respond(&"ok").unwrap();
+
+        let response = rx.recv().expect("responder should have dispatched");
+        assert_eq!(
+            response.session_id.as_ref().map(String::as_str),
+            Some("session-1")
+        );
+        match response.payload {
+            LoopbackResponsePayload::Success(value) => assert_eq!(value, "ok"),
+            other => panic!("expected a success response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dropping_an_undispatched_responder_sends_an_internal_error() {
+        let (mut loopback_command, rx) =
+            LoopbackCommand::new("Page.navigate", Navigate { url: String::new() }, None, None);
+        let responder = loopback_command.take_responder();
+
+        drop(responder);
+
+        let response = rx.recv().expect("drop should have dispatched a response");
+        match response.payload {
+            LoopbackResponsePayload::Error(error) => {
+                assert_eq!(error.code, CdpError::internal_error().code)
+            }
+            other => panic!("expected an error response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn broadcast_reaches_every_subscriber() {
+        let sender = LoopbackEventSender::new();
+        let rx1 = sender.subscribe();
+        let rx2 = sender.subscribe();
+
+        sender.send_event(&DummyEvent).unwrap();
+
+        assert_eq!(rx1.recv().unwrap().payload, serde_json::Value::Null);
+        assert_eq!(rx2.recv().unwrap().payload, serde_json::Value::Null);
+    }
+}
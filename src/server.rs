@@ -4,8 +4,10 @@
 
 use serde::Serialize;
 use std::error::Error;
+use std::time::Instant;
 
-use traits::{DeserializeCdpCommand, SerializeCdpEvent};
+use crate::error::CdpError;
+use crate::traits::{DeserializeCdpCommand, SerializeCdpEvent};
 
 /// The encoded command type for the server side of this transport.
 ///
@@ -20,6 +22,24 @@ pub trait CdpServerCommand: Sized {
     /// Get the name of the encoded command.
     fn command_name(&self) -> &str;
 
+    /// Get the CDP `sessionId` this command is targeted at, if any.
+    ///
+    /// Real CDP multiplexes commands for several attached targets (pages,
+    /// workers, ...) over a single client connection by tagging each
+    /// `MethodCall` with an optional `sessionId`, obtained from
+    /// `Target.attachToTarget`. A command with no session id is targeting the
+    /// browser-level connection itself.
+    fn session_id(&self) -> Option<&str>;
+
+    /// Get the point in time by which this command must be responded to, if
+    /// one was decoded from the protocol or configured by the server.
+    ///
+    /// A server should treat a command whose deadline has passed before it
+    /// was dispatched the same as one that can never succeed, and answer it
+    /// with `CdpServerResponder::deadline_exceeded` instead of carrying out
+    /// the (now pointless) work.
+    fn deadline(&self) -> Option<Instant>;
+
     /// Attempt to deserialize the encoded command into a specific command type.
     ///
     /// If the command type recognizes the name of this command and attempts to
@@ -44,6 +64,10 @@ pub trait CdpServerCommand: Sized {
 /// of the response methods before allowing the instance to drop, as well as the
 /// case where a response method was called but the *encoding* of the response
 /// failed.
+///
+/// Implementations must echo back the `sessionId` of the command this
+/// responder was created for on every response frame they send, so the
+/// client can tell which attached target the response belongs to.
 pub trait CdpServerResponder: Sized + Drop {
     /// The type of a respond error.
     type Error: Error;
@@ -53,10 +77,17 @@ pub trait CdpServerResponder: Sized + Drop {
     where
         R: Serialize;
 
+    /// Send back a structured CDP error, as produced by the canonical
+    /// constructors on `CdpError` (or a custom one with its own `code` and
+    /// `data`).
+    fn respond_error(self, error: CdpError) -> Result<(), Self::Error>;
+
     /// Send back a failure response, indicating to the client that the command
     /// was not recognized or is not supported by this particular server
     /// implementation.
-    fn command_not_found(self) -> Result<(), Self::Error>;
+    fn command_not_found(self) -> Result<(), Self::Error> {
+        self.respond_error(CdpError::method_not_found())
+    }
 
     /// Send back a failure response, indicating to the client that while the
     /// command was
@@ -65,7 +96,9 @@ pub trait CdpServerResponder: Sized + Drop {
     /// ```rust,ignore
     /// responder.invalid_params("url: string value expected".into())
     /// ```
-    fn invalid_params(self, message: &str) -> Result<(), Self::Error>;
+    fn invalid_params(self, message: &str) -> Result<(), Self::Error> {
+        self.respond_error(CdpError::invalid_params(message.to_owned()))
+    }
 
     /// Send back a failure response, indicating to the client that while the
     /// command was understood and the server implementation is functioning
@@ -75,12 +108,39 @@ pub trait CdpServerResponder: Sized + Drop {
     /// responder.server_error("The URL specified is invalid.".into())
     /// responder.server_error("The TLS handshake failed.".into())
     /// ```
-    fn server_error(self, message: &str) -> Result<(), Self::Error>;
+    fn server_error(self, message: &str) -> Result<(), Self::Error> {
+        self.respond_error(CdpError::server_error(message.to_owned()))
+    }
 
     /// Send back a failure response, indicating to the client that an internal,
     /// server implementation-specific error occurred (for example, an IPC
     /// channel disconnected unexpectedly).
-    fn internal_error(self) -> Result<(), Self::Error>;
+    fn internal_error(self) -> Result<(), Self::Error> {
+        self.respond_error(CdpError::internal_error())
+    }
+
+    /// Send back a failure response, indicating to the client that the
+    /// server is flow-controlling its connection and is not accepting new
+    /// commands until some of the ones already outstanding are answered.
+    ///
+    /// Implementations should use this instead of queuing the command
+    /// unboundedly once `ThrottleConfig::max_in_flight` outstanding commands
+    /// are already awaiting a response. It counts as this responder's one
+    /// allowed dispatch.
+    fn throttled(self) -> Result<(), Self::Error> {
+        self.respond_error(CdpError::throttled())
+    }
+
+    /// Send back a failure response, indicating to the client that the
+    /// command's deadline (see `CdpServerCommand::deadline`) elapsed before
+    /// the server could dispatch a real response.
+    ///
+    /// It counts as this responder's one allowed dispatch; a real response
+    /// computed after the deadline has passed should be dropped instead of
+    /// sent.
+    fn deadline_exceeded(self) -> Result<(), Self::Error> {
+        self.respond_error(CdpError::deadline_exceeded())
+    }
 }
 
 /// A cloneable handle which grants the ability to push events from the server
@@ -93,4 +153,18 @@ pub trait CdpServerEventSender: Sized + Clone {
     fn send_event<E>(&self, event: &E) -> Result<(), Self::Error>
     where
         E: SerializeCdpEvent;
+
+    /// Push an event to the client, scoped to a particular attached target.
+    ///
+    /// `session_id` should match the `sessionId` a client received from
+    /// `Target.attachToTarget`; passing `None` addresses the browser-level
+    /// connection itself. This lets a single event sender multiplex events
+    /// for many targets over one client connection, the way real CDP does.
+    fn send_event_to_session<E>(
+        &self,
+        session_id: Option<&str>,
+        event: &E,
+    ) -> Result<(), Self::Error>
+    where
+        E: SerializeCdpEvent;
 }
@@ -0,0 +1,161 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::server::CdpServerEventSender;
+use crate::traits::SerializeCdpEvent;
+
+/// Maps CDP `sessionId`s to the logical target they were attached to, and
+/// fans events out to every attached target over a shared
+/// `CdpServerEventSender`.
+///
+/// This is the bookkeeping a `Target` domain implementation needs once
+/// `Target.attachToTarget` can produce more than one session over a single
+/// client connection, so users don't have to hand-roll the id plumbing in
+/// every responder.
+pub struct SessionRegistry<T> {
+    sessions: Mutex<HashMap<String, T>>,
+}
+
+impl<T> SessionRegistry<T> {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        SessionRegistry {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Attach a logical target under the given session id, returning the
+    /// previously attached target, if any.
+    pub fn attach(&self, session_id: String, target: T) -> Option<T> {
+        self.sessions.lock().unwrap().insert(session_id, target)
+    }
+
+    /// Detach the target behind a session id, returning it if it existed.
+    pub fn detach(&self, session_id: &str) -> Option<T> {
+        self.sessions.lock().unwrap().remove(session_id)
+    }
+
+    /// Look up the ids of every currently attached session.
+    pub fn session_ids(&self) -> Vec<String> {
+        self.sessions.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Send `event` to the target attached under `session_id` via `sender`,
+    /// doing nothing if no target is attached under that id.
+    pub fn send_to<S, E>(&self, sender: &S, session_id: &str, event: &E) -> Result<(), S::Error>
+    where
+        S: CdpServerEventSender,
+        E: SerializeCdpEvent,
+    {
+        if !self.sessions.lock().unwrap().contains_key(session_id) {
+            return Ok(());
+        }
+        sender.send_event_to_session(Some(session_id), event)
+    }
+
+    /// Fan `event` out to every currently attached session.
+    pub fn broadcast<S, E>(&self, sender: &S, event: &E) -> Result<(), S::Error>
+    where
+        S: CdpServerEventSender,
+        E: SerializeCdpEvent,
+    {
+        for session_id in self.session_ids() {
+            sender.send_event_to_session(Some(&session_id), event)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Default for SessionRegistry<T> {
+    fn default() -> Self {
+        SessionRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CdpError;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct RecordingSender {
+        sent: Arc<Mutex<Vec<Option<String>>>>,
+    }
+
+    impl CdpServerEventSender for RecordingSender {
+        type Error = CdpError;
+
+        fn send_event<E>(&self, event: &E) -> Result<(), Self::Error>
+        where
+            E: SerializeCdpEvent,
+        {
+            self.send_event_to_session(None, event)
+        }
+
+        fn send_event_to_session<E>(
+            &self,
+            session_id: Option<&str>,
+            _event: &E,
+        ) -> Result<(), Self::Error>
+        where
+            E: SerializeCdpEvent,
+        {
+            self.sent
+                .lock()
+                .unwrap()
+                .push(session_id.map(str::to_owned));
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize)]
+    struct DummyEvent;
+
+    impl SerializeCdpEvent for DummyEvent {
+        const NAME: &'static str = "Dummy.event";
+    }
+
+    #[test]
+    fn attach_returns_previous_target() {
+        let registry = SessionRegistry::new();
+        assert_eq!(registry.attach("s1".to_owned(), 1), None);
+        assert_eq!(registry.attach("s1".to_owned(), 2), Some(1));
+    }
+
+    #[test]
+    fn detach_removes_and_returns_the_target() {
+        let registry = SessionRegistry::new();
+        registry.attach("s1".to_owned(), "target".to_owned());
+        assert_eq!(registry.detach("s1"), Some("target".to_owned()));
+        assert_eq!(registry.detach("s1"), None);
+    }
+
+    #[test]
+    fn broadcast_reaches_every_attached_session() {
+        let registry = SessionRegistry::new();
+        registry.attach("s1".to_owned(), ());
+        registry.attach("s2".to_owned(), ());
+        let sender = RecordingSender::default();
+
+        registry.broadcast(&sender, &DummyEvent).unwrap();
+
+        let mut sent = sender.sent.lock().unwrap().clone();
+        sent.sort();
+        assert_eq!(sent, vec![Some("s1".to_owned()), Some("s2".to_owned())]);
+    }
+
+    #[test]
+    fn send_to_is_a_noop_for_an_unattached_session() {
+        let registry: SessionRegistry<()> = SessionRegistry::new();
+        let sender = RecordingSender::default();
+
+        registry.send_to(&sender, "missing", &DummyEvent).unwrap();
+
+        assert!(sender.sent.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,456 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A transport over this process's stdin/stdout (or any other pair of
+//! streams, such as a named pipe), guarded by a signed handshake so only an
+//! authorized parent process can drive it.
+//!
+//! On startup, the transport generates a random nonce and writes it to the
+//! peer as a hex-encoded line, then reads back a line containing the peer's
+//! HMAC-SHA256 signature of that nonce, keyed by a shared secret passed
+//! out-of-band (an environment variable or an inherited file descriptor).
+//! Only a peer that also knows the secret can produce that signature, so it
+//! is compared (in constant time) against the signature this side computes
+//! itself; a mismatch aborts before any `CdpServerCommand` is ever
+//! dispatched. After the handshake, commands, responses, and events are
+//! framed one JSON object per line, reusing the same `MethodCall`-shaped
+//! payloads as `framing::FramedCommand`.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde_json;
+use sha2::Sha256;
+use std::env;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::error::CdpError;
+use crate::framing::FramedCommand;
+use crate::server::{CdpServerEventSender, CdpServerResponder};
+use crate::traits::SerializeCdpEvent;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Why the handshake failed.
+#[derive(Debug)]
+pub enum HandshakeError {
+    /// No shared secret was available via `CDP_STDIO_SECRET` or
+    /// `CDP_STDIO_SECRET_FD`.
+    NoSharedSecret,
+    /// The signature line received from the peer wasn't valid hex.
+    BadHex,
+    /// The peer's signature over the nonce didn't match; it doesn't know
+    /// the shared secret.
+    SignatureMismatch,
+    /// Reading or writing the handshake failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HandshakeError::NoSharedSecret => write!(f, "no shared secret configured"),
+            HandshakeError::BadHex => write!(f, "expected hex-encoded data"),
+            HandshakeError::SignatureMismatch => {
+                write!(f, "peer failed to prove knowledge of the shared secret")
+            }
+            HandshakeError::Io(ref e) => write!(f, "handshake I/O error: {}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for HandshakeError {
+    fn description(&self) -> &str {
+        "stdio handshake failed"
+    }
+}
+
+impl From<io::Error> for HandshakeError {
+    fn from(e: io::Error) -> Self {
+        HandshakeError::Io(e)
+    }
+}
+
+/// Read the shared secret passed out-of-band by the supervisor.
+///
+/// If `CDP_STDIO_SECRET_FD` is set, it names an inherited, already-open file
+/// descriptor to read the secret from (so it never appears in `ps` output or
+/// this process's environment dump). Otherwise, `CDP_STDIO_SECRET` is used
+/// directly.
+fn shared_secret() -> Result<Vec<u8>, HandshakeError> {
+    if let Ok(fd) = env::var("CDP_STDIO_SECRET_FD") {
+        let fd: i32 = fd.parse().map_err(|_| HandshakeError::NoSharedSecret)?;
+        let mut file = unsafe_file_from_fd(fd);
+        let mut secret = Vec::new();
+        file.read_to_end(&mut secret)?;
+        while secret.last() == Some(&b'\n') || secret.last() == Some(&b'\r') {
+            secret.pop();
+        }
+        return Ok(secret);
+    }
+    if let Ok(secret) = env::var("CDP_STDIO_SECRET") {
+        return Ok(secret.into_bytes());
+    }
+    Err(HandshakeError::NoSharedSecret)
+}
+
+#[cfg(unix)]
+fn unsafe_file_from_fd(fd: i32) -> File {
+    use std::os::unix::io::FromRawFd;
+    unsafe { File::from_raw_fd(fd) }
+}
+
+#[cfg(not(unix))]
+fn unsafe_file_from_fd(_fd: i32) -> File {
+    panic!("CDP_STDIO_SECRET_FD is only supported on unix");
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>, HandshakeError> {
+    if text.len() % 2 != 0 {
+        return Err(HandshakeError::BadHex);
+    }
+    let mut out = Vec::with_capacity(text.len() / 2);
+    let bytes = text.as_bytes();
+    for chunk in bytes.chunks(2) {
+        let pair = ::std::str::from_utf8(chunk).map_err(|_| HandshakeError::BadHex)?;
+        let byte = u8::from_str_radix(pair, 16).map_err(|_| HandshakeError::BadHex)?;
+        out.push(byte);
+    }
+    Ok(out)
+}
+
+/// Why `StdioTransport::next_command` failed to produce a command.
+#[derive(Debug)]
+pub enum NextCommandError {
+    /// Reading the line itself failed.
+    Io(io::Error),
+    /// A line was read, but it wasn't a well-formed framed command. The
+    /// stream is still positioned at the next line, so the caller may
+    /// continue reading commands after reporting this one as failed.
+    Decode(crate::framing::FrameDecodeError),
+}
+
+impl fmt::Display for NextCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NextCommandError::Io(ref e) => write!(f, "{}", e),
+            NextCommandError::Decode(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ::std::error::Error for NextCommandError {
+    fn description(&self) -> &str {
+        "failed to read the next command"
+    }
+}
+
+impl From<io::Error> for NextCommandError {
+    fn from(e: io::Error) -> Self {
+        NextCommandError::Io(e)
+    }
+}
+
+impl From<crate::framing::FrameDecodeError> for NextCommandError {
+    fn from(e: crate::framing::FrameDecodeError) -> Self {
+        NextCommandError::Decode(e)
+    }
+}
+
+/// Compare two byte strings for equality without branching on the position
+/// of the first difference, so the time taken doesn't leak how much of a
+/// guessed signature was correct.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+    diff == 0
+}
+
+/// A line-delimited JSON transport over stdin/stdout or an equivalent pair
+/// of streams, reachable only after `handshake` has validated the peer.
+pub struct StdioTransport<R, W> {
+    reader: BufReader<R>,
+    writer: Arc<Mutex<W>>,
+}
+
+impl<R: Read, W: Write> StdioTransport<R, W> {
+    /// Perform the signed handshake over `reader`/`writer`, refusing to
+    /// proceed until the peer proves it knows the shared secret.
+    ///
+    /// This side generates a random nonce and writes it to the peer as a
+    /// hex-encoded line, then reads back a line containing the peer's
+    /// HMAC-SHA256 signature of that nonce under `shared_secret()`. The
+    /// signature is checked in constant time; only a peer that also knows
+    /// the secret can have produced it.
+    pub fn handshake(reader: R, writer: W) -> Result<Self, HandshakeError> {
+        let secret = shared_secret()?;
+        let mut reader = BufReader::new(reader);
+        let mut writer = writer;
+
+        let mut nonce = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        writeln!(writer, "{}", encode_hex(&nonce))?;
+        writer.flush()?;
+
+        let mut signature_line = String::new();
+        reader.read_line(&mut signature_line)?;
+        let received_signature = decode_hex(signature_line.trim())?;
+
+        let mut mac = HmacSha256::new_varkey(&secret).expect("HMAC accepts any key length");
+        mac.input(&nonce);
+        let expected_signature = mac.result().code();
+
+        if !constant_time_eq(expected_signature.as_slice(), &received_signature) {
+            return Err(HandshakeError::SignatureMismatch);
+        }
+
+        Ok(StdioTransport {
+            reader,
+            writer: Arc::new(Mutex::new(writer)),
+        })
+    }
+
+    /// Read and decode the next command line, blocking until one arrives.
+    ///
+    /// Returns `Ok(None)` at end of stream (the peer closed its end). A
+    /// malformed line is reported as `Err` rather than folded into end of
+    /// stream, so a caller can skip just that command instead of tearing
+    /// down the whole connection.
+    pub fn next_command(&mut self) -> Result<Option<FramedCommand>, NextCommandError> {
+        let mut line = String::new();
+        let n = self.reader.read_line(&mut line)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        Ok(Some(FramedCommand::decode(line.trim().as_bytes())?))
+    }
+
+    /// Get a responder for the command with the given id and session id.
+    pub fn responder(&self, id: u64, session_id: Option<String>) -> StdioResponder<W> {
+        StdioResponder {
+            writer: Some(self.writer.clone()),
+            id,
+            session_id,
+        }
+    }
+
+    /// Get a cloneable event sender writing onto this transport's stream.
+    pub fn event_sender(&self) -> StdioEventSender<W> {
+        StdioEventSender {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+fn write_line<W: Write>(writer: &Mutex<W>, value: &serde_json::Value) -> io::Result<()> {
+    let mut writer = writer.lock().unwrap();
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// A `CdpServerResponder` that writes one JSON response line per dispatch.
+pub struct StdioResponder<W: Write> {
+    writer: Option<Arc<Mutex<W>>>,
+    id: u64,
+    session_id: Option<String>,
+}
+
+impl<W: Write> StdioResponder<W> {
+    fn dispatch(&mut self, body: serde_json::Value) -> Result<(), io::Error> {
+        let writer = self
+            .writer
+            .take()
+            .expect("CdpServerResponder contract violated: dispatched more than once");
+        let mut frame = serde_json::json!({ "id": self.id });
+        if let Some(ref session_id) = self.session_id {
+            frame["sessionId"] = serde_json::Value::String(session_id.clone());
+        }
+        for (key, value) in body.as_object().into_iter().flat_map(|m| m.iter()) {
+            frame[key.as_str()] = value.clone();
+        }
+        write_line(&writer, &frame)
+    }
+}
+
+impl<W: Write> Drop for StdioResponder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let error = CdpError::internal_error();
+            let _ = self.dispatch(serde_json::json!({ "error": error }));
+        }
+    }
+}
+
+impl<W: Write> CdpServerResponder for StdioResponder<W> {
+    type Error = io::Error;
+
+    fn respond<R>(mut self, response: &R) -> Result<(), Self::Error>
+    where
+        R: ::serde::Serialize,
+    {
+        let value = serde_json::to_value(response)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.dispatch(serde_json::json!({ "result": value }))
+    }
+
+    fn respond_error(mut self, error: CdpError) -> Result<(), Self::Error> {
+        self.dispatch(serde_json::json!({ "error": error }))
+    }
+}
+
+/// A cloneable `CdpServerEventSender` that writes one JSON event line per
+/// push onto the shared transport stream.
+pub struct StdioEventSender<W> {
+    writer: Arc<Mutex<W>>,
+}
+
+// Hand-written so cloning a `StdioEventSender<W>` never requires `W: Clone`:
+// only the `Arc` around the shared writer is cloned, not `W` itself.
+impl<W> Clone for StdioEventSender<W> {
+    fn clone(&self) -> Self {
+        StdioEventSender {
+            writer: self.writer.clone(),
+        }
+    }
+}
+
+impl<W: Write> CdpServerEventSender for StdioEventSender<W> {
+    type Error = io::Error;
+
+    fn send_event<E>(&self, event: &E) -> Result<(), Self::Error>
+    where
+        E: SerializeCdpEvent,
+    {
+        self.send_event_to_session(None, event)
+    }
+
+    fn send_event_to_session<E>(
+        &self,
+        session_id: Option<&str>,
+        event: &E,
+    ) -> Result<(), Self::Error>
+    where
+        E: SerializeCdpEvent,
+    {
+        let params = serde_json::to_value(event)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut frame = serde_json::json!({ "method": E::NAME, "params": params });
+        if let Some(session_id) = session_id {
+            frame["sessionId"] = serde_json::Value::String(session_id.to_owned());
+        }
+        write_line(&self.writer, &frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn spawn_signing_peer(
+        port: u16,
+        secret: &'static [u8],
+        mutate_nonce: bool,
+    ) -> thread::JoinHandle<()> {
+        thread::spawn(move || {
+            let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let mut reader = BufReader::new(client.try_clone().unwrap());
+            let mut nonce_line = String::new();
+            reader.read_line(&mut nonce_line).unwrap();
+            let mut nonce = decode_hex(nonce_line.trim()).unwrap();
+            if mutate_nonce {
+                nonce[0] ^= 0xff;
+            }
+
+            let mut mac = HmacSha256::new_varkey(secret).expect("HMAC accepts any key length");
+            mac.input(&nonce);
+            let signature = mac.result().code();
+            writeln!(client, "{}", encode_hex(&signature)).unwrap();
+        })
+    }
+
+    #[test]
+    fn handshake_accepts_a_correct_signature() {
+        env::set_var("CDP_STDIO_SECRET", "shared-secret");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let peer = spawn_signing_peer(port, b"shared-secret", false);
+
+        let (stream, _) = listener.accept().unwrap();
+        let reader = stream.try_clone().unwrap();
+        let transport = StdioTransport::handshake(reader, stream);
+
+        peer.join().unwrap();
+        assert!(transport.is_ok());
+    }
+
+    #[test]
+    fn handshake_rejects_a_signature_over_the_wrong_secret() {
+        env::set_var("CDP_STDIO_SECRET", "shared-secret");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let peer = spawn_signing_peer(port, b"not-the-secret", false);
+
+        let (stream, _) = listener.accept().unwrap();
+        let reader = stream.try_clone().unwrap();
+        let transport = StdioTransport::handshake(reader, stream);
+
+        peer.join().unwrap();
+        assert!(matches!(transport, Err(HandshakeError::SignatureMismatch)));
+    }
+
+    #[test]
+    fn handshake_rejects_a_signature_over_the_wrong_nonce() {
+        env::set_var("CDP_STDIO_SECRET", "shared-secret");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let peer = spawn_signing_peer(port, b"shared-secret", true);
+
+        let (stream, _) = listener.accept().unwrap();
+        let reader = stream.try_clone().unwrap();
+        let transport = StdioTransport::handshake(reader, stream);
+
+        peer.join().unwrap();
+        assert!(matches!(transport, Err(HandshakeError::SignatureMismatch)));
+    }
+
+    #[test]
+    fn handshake_rejects_malformed_hex() {
+        env::set_var("CDP_STDIO_SECRET", "shared-secret");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let peer = thread::spawn(move || {
+            let mut client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            let mut reader = BufReader::new(client.try_clone().unwrap());
+            let mut nonce_line = String::new();
+            reader.read_line(&mut nonce_line).unwrap();
+            writeln!(client, "not-valid-hex").unwrap();
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let reader = stream.try_clone().unwrap();
+        let transport = StdioTransport::handshake(reader, stream);
+
+        peer.join().unwrap();
+        assert!(matches!(transport, Err(HandshakeError::BadHex)));
+    }
+}
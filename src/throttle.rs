@@ -0,0 +1,171 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+/// Configuration for the server-side flow-control layer.
+#[derive(Clone, Copy, Debug)]
+pub struct ThrottleConfig {
+    /// The maximum number of commands that may be outstanding (handed out
+    /// to a handler but not yet responded to) on one connection at a time.
+    /// Commands received while this limit is reached should be answered
+    /// with `CdpServerResponder::throttled` rather than queued.
+    pub max_in_flight: usize,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        ThrottleConfig {
+            max_in_flight: 32,
+        }
+    }
+}
+
+/// Tracks the number of commands outstanding on a connection against a
+/// `ThrottleConfig`, so the dispatch layer can decide whether to hand a new
+/// command to a handler or immediately answer it with `throttled`.
+///
+/// The count is incremented when a command is handed out and decremented
+/// when the matching `OutstandingGuard` is dropped, which should happen
+/// exactly when the responder for that command dispatches (throttled,
+/// deadline-exceeded, or a real response all count as the single allowed
+/// dispatch). The count never goes negative.
+#[derive(Clone)]
+pub struct Throttle {
+    config: ThrottleConfig,
+    outstanding: Arc<AtomicUsize>,
+}
+
+impl Throttle {
+    /// Create a new, empty throttle from the given configuration.
+    pub fn new(config: ThrottleConfig) -> Self {
+        Throttle {
+            config,
+            outstanding: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// The number of commands currently outstanding.
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::SeqCst)
+    }
+
+    /// Attempt to admit one more outstanding command.
+    ///
+    /// Returns `Some(guard)` if the connection is under
+    /// `ThrottleConfig::max_in_flight`, incrementing the outstanding count
+    /// for as long as the guard lives. Returns `None`, without incrementing
+    /// anything, if the connection is already at its limit; the caller
+    /// should respond to the command with `CdpServerResponder::throttled`.
+    pub fn try_admit(&self) -> Option<OutstandingGuard> {
+        loop {
+            let current = self.outstanding.load(Ordering::SeqCst);
+            if current >= self.config.max_in_flight {
+                return None;
+            }
+            if self
+                .outstanding
+                .compare_exchange(current, current + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                return Some(OutstandingGuard {
+                    outstanding: self.outstanding.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// Marks one command as outstanding until dropped, at which point the count
+/// it was admitted under is decremented.
+pub struct OutstandingGuard {
+    outstanding: Arc<AtomicUsize>,
+}
+
+impl Drop for OutstandingGuard {
+    fn drop(&mut self) {
+        self.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Watches a command's deadline and invokes a callback if it elapses before
+/// being disarmed.
+///
+/// This is meant to back `CdpServerCommand::deadline`: arm a watchdog when a
+/// command with a deadline is handed to a handler, and disarm it once the
+/// responder actually dispatches. If the deadline elapses first, the
+/// watchdog's callback should auto-dispatch a `deadline_exceeded` response
+/// and mark the responder as spent, so a real response computed afterwards
+/// is dropped instead of sent.
+pub struct DeadlineWatchdog {
+    fired: Arc<AtomicUsize>,
+}
+
+impl DeadlineWatchdog {
+    /// Spawn a watchdog that calls `on_expire` if `deadline` passes before
+    /// `disarm` is called.
+    pub fn arm<F>(deadline: Instant, on_expire: F) -> Self
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let fired_thread = fired.clone();
+        thread::spawn(move || {
+            let now = Instant::now();
+            if deadline > now {
+                thread::sleep(deadline - now);
+            }
+            if fired_thread
+                .compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                on_expire();
+            }
+        });
+        DeadlineWatchdog { fired }
+    }
+
+    /// Prevent the watchdog's callback from firing, if the deadline has not
+    /// already elapsed. Returns `true` if the watchdog was disarmed in time.
+    pub fn disarm(&self) -> bool {
+        self.fired
+            .compare_exchange(0, 1, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn admits_up_to_the_configured_limit() {
+        let throttle = Throttle::new(ThrottleConfig { max_in_flight: 2 });
+        let a = throttle.try_admit().expect("first should be admitted");
+        let b = throttle.try_admit().expect("second should be admitted");
+        assert!(throttle.try_admit().is_none());
+
+        drop(a);
+        let c = throttle.try_admit().expect("slot freed by drop should be reused");
+        drop(b);
+        drop(c);
+        assert_eq!(throttle.outstanding(), 0);
+    }
+
+    #[test]
+    fn disarm_prevents_expiry_callback() {
+        let fired = Arc::new(AtomicUsize::new(0));
+        let fired_check = fired.clone();
+        let watchdog = DeadlineWatchdog::arm(Instant::now() + Duration::from_millis(50), move || {
+            fired_check.fetch_add(1, Ordering::SeqCst);
+        });
+        assert!(watchdog.disarm());
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(fired.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,48 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+use serde::{Deserialize, Serialize};
+
+/// A CDP command that can be deserialized from its encoded, on-the-wire form.
+///
+/// Implementations are usually generated for an enum of every command a
+/// particular CDP domain supports, via the `cdp-derive` crate.
+///
+/// The `'static` bound lets transports that skip serialization entirely
+/// (such as an in-process, same-type transport) recover a concrete command
+/// type from a type-erased value via `std::any::Any` downcasting.
+pub trait DeserializeCdpCommand<'de>: Sized + Deserialize<'de> + 'static {
+    /// The type of the response to this command.
+    type Response: Serialize;
+
+    /// The CDP method name this command corresponds to, e.g. `"Page.navigate"`.
+    const NAME: &'static str;
+}
+
+/// A CDP event that can be serialized to its encoded, on-the-wire form.
+pub trait SerializeCdpEvent: Serialize {
+    /// The CDP method name this event corresponds to, e.g. `"Page.loadEventFired"`.
+    const NAME: &'static str;
+}
+
+/// A CDP command that can be serialized to its encoded, on-the-wire form, for
+/// use on the client side of a connection.
+pub trait SerializeCdpCommand: Serialize {
+    /// The type of the response to this command.
+    type Response: for<'de> Deserialize<'de>;
+
+    /// The CDP method name this command corresponds to, e.g. `"Page.navigate"`.
+    const NAME: &'static str;
+}
+
+/// A CDP event that can be deserialized from its encoded, on-the-wire form,
+/// for use on the client side of a connection.
+///
+/// The `'static` bound mirrors `DeserializeCdpCommand`, for the same reason:
+/// it lets zero-serialization transports recover a concrete event type via
+/// `std::any::Any` downcasting.
+pub trait DeserializeCdpEvent<'de>: Sized + Deserialize<'de> + 'static {
+    /// The CDP method name this event corresponds to, e.g. `"Page.loadEventFired"`.
+    const NAME: &'static str;
+}
@@ -0,0 +1,306 @@
+// This Source Code Form is subject to the terms of the Mozilla Public License,
+// v. 2.0. If a copy of the MPL was not distributed with this file, You can
+// obtain one at http://mozilla.org/MPL/2.0/.
+
+//! A `CdpClient` implementation over a WebSocket, the standard CDP wire
+//! transport (`ws://host:port/devtools/...`).
+
+use futures::channel::{mpsc, oneshot};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::future::Future;
+use std::net::{Shutdown, TcpStream};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
+use std::thread;
+use tungstenite::{self, Message};
+use url::Url;
+
+use crate::client::{CdpClient, CdpClientEvent};
+use crate::error::CdpError;
+use crate::traits::{DeserializeCdpEvent, SerializeCdpCommand};
+
+/// An error connecting to, or communicating over, a CDP WebSocket.
+#[derive(Debug)]
+pub enum WsError {
+    InvalidUrl(String),
+    Io(::std::io::Error),
+    /// The WebSocket handshake itself failed, stringified at the boundary
+    /// since its concrete error type differs across `tungstenite` releases.
+    Connect(String),
+    Closed,
+}
+
+impl fmt::Display for WsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WsError::InvalidUrl(ref url) => write!(f, "invalid websocket url: {}", url),
+            WsError::Io(ref e) => write!(f, "I/O error connecting: {}", e),
+            WsError::Connect(ref message) => write!(f, "failed to connect: {}", message),
+            WsError::Closed => write!(f, "the websocket connection is closed"),
+        }
+    }
+}
+
+impl error::Error for WsError {
+    fn description(&self) -> &str {
+        "websocket transport error"
+    }
+}
+
+/// Shuts down the underlying TCP connection when the last clone of it is
+/// dropped, which unblocks a thread parked in a blocking read on the same
+/// socket so it can notice the connection is gone and exit, instead of
+/// leaking forever.
+struct ShutdownOnDrop(TcpStream);
+
+impl Drop for ShutdownOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.shutdown(Shutdown::Both);
+    }
+}
+
+/// An encoded event received over a `WsClient` connection.
+pub struct WsClientEvent {
+    method: String,
+    params: Value,
+}
+
+impl CdpClientEvent for WsClientEvent {
+    type Error = serde_json::Error;
+
+    fn event_name(&self) -> &str {
+        &self.method
+    }
+
+    fn deserialize_event<E>(self) -> Result<Result<E, Self::Error>, Self>
+    where
+        E: for<'de> DeserializeCdpEvent<'de>,
+    {
+        if self.method != E::NAME {
+            return Err(self);
+        }
+        Ok(serde_json::from_value(self.params))
+    }
+}
+
+struct Pending {
+    by_id: Mutex<HashMap<u64, oneshot::Sender<Result<Value, CdpError>>>>,
+}
+
+/// A cloneable `CdpClient` backed by a single WebSocket connection.
+///
+/// Cloning reuses the one underlying connection and its background I/O
+/// threads; dropping the last clone drops the outgoing channel (which stops
+/// the writer thread) and shuts down the underlying TCP connection (which
+/// unblocks and stops the reader thread).
+#[derive(Clone)]
+pub struct WsClient {
+    next_id: Arc<AtomicU64>,
+    outgoing: std_mpsc::Sender<Message>,
+    pending: Arc<Pending>,
+    subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<WsClientEvent>>>>,
+    // Held only for its Drop side effect; never read otherwise.
+    _shutdown_on_drop: Arc<ShutdownOnDrop>,
+}
+
+impl WsClient {
+    /// Connect to a CDP endpoint, e.g. `ws://127.0.0.1:9222/devtools/browser/...`.
+    pub fn connect(url: &str) -> Result<Self, WsError> {
+        let parsed = Url::parse(url).map_err(|_| WsError::InvalidUrl(url.to_owned()))?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| WsError::InvalidUrl(url.to_owned()))?;
+        let port = parsed.port_or_known_default().unwrap_or(80);
+        let tcp = TcpStream::connect((host, port)).map_err(WsError::Io)?;
+        let shutdown_handle = tcp.try_clone().map_err(WsError::Io)?;
+
+        let (socket, _response) =
+            tungstenite::client(parsed, tcp).map_err(|e| WsError::Connect(e.to_string()))?;
+        // Reads time out periodically so the reader thread releases the
+        // socket lock and the writer thread gets a chance to send.
+        shutdown_handle
+            .set_read_timeout(Some(::std::time::Duration::from_millis(10)))
+            .map_err(WsError::Io)?;
+        let socket = Arc::new(Mutex::new(socket));
+
+        let (outgoing_tx, outgoing_rx) = std_mpsc::channel::<Message>();
+        let pending = Arc::new(Pending {
+            by_id: Mutex::new(HashMap::new()),
+        });
+        let subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<WsClientEvent>>>> =
+            Arc::new(Mutex::new(Vec::new()));
+
+        // The writer thread owns sending so that reads and writes to the
+        // socket never interleave from two threads at once.
+        let writer_socket = socket.clone();
+        thread::spawn(move || {
+            for message in outgoing_rx {
+                if writer_socket.lock().unwrap().write_message(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let reader_pending = pending.clone();
+        let reader_subscribers = subscribers.clone();
+        let reader_socket = socket.clone();
+        thread::spawn(move || loop {
+            // Bind the result first so the lock is released before matching.
+            let result = reader_socket.lock().unwrap().read_message();
+            let message = match result {
+                Ok(message) => message,
+                Err(tungstenite::Error::Io(ref e))
+                    if e.kind() == ::std::io::ErrorKind::WouldBlock
+                        || e.kind() == ::std::io::ErrorKind::TimedOut =>
+                {
+                    thread::sleep(::std::time::Duration::from_millis(1));
+                    continue;
+                }
+                Err(_) => break,
+            };
+            let text = match message {
+                Message::Text(text) => text,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            let value: Value = match serde_json::from_str(&text) {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            if let Some(id) = value.get("id").and_then(Value::as_u64) {
+                let sender = reader_pending.by_id.lock().unwrap().remove(&id);
+                if let Some(sender) = sender {
+                    let result = match value.get("error") {
+                        Some(error) => Err(serde_json::from_value(error.clone())
+                            .unwrap_or_else(|_| CdpError::internal_error())),
+                        None => Ok(value.get("result").cloned().unwrap_or(Value::Null)),
+                    };
+                    let _ = sender.send(result);
+                }
+            } else if let Some(method) = value.get("method").and_then(Value::as_str) {
+                let params = value.get("params").cloned().unwrap_or(Value::Null);
+                let event = WsClientEvent {
+                    method: method.to_owned(),
+                    params,
+                };
+                let mut subscribers = reader_subscribers.lock().unwrap();
+                subscribers.retain(|sender| sender.unbounded_send(WsClientEvent {
+                    method: event.method.clone(),
+                    params: event.params.clone(),
+                }).is_ok());
+            }
+        });
+
+        Ok(WsClient {
+            next_id: Arc::new(AtomicU64::new(1)),
+            outgoing: outgoing_tx,
+            pending,
+            subscribers,
+            _shutdown_on_drop: Arc::new(ShutdownOnDrop(shutdown_handle)),
+        })
+    }
+}
+
+impl CdpClient for WsClient {
+    type Event = WsClientEvent;
+    type EventStream = mpsc::UnboundedReceiver<WsClientEvent>;
+
+    fn command<C>(
+        &self,
+        cmd: &C,
+    ) -> Pin<Box<dyn Future<Output = Result<C::Response, CdpError>> + Send>>
+    where
+        C: SerializeCdpCommand,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = oneshot::channel();
+        self.pending.by_id.lock().unwrap().insert(id, tx);
+
+        let call = CallFrame {
+            id,
+            method: C::NAME,
+            params: cmd,
+        };
+        let sent = serde_json::to_string(&call)
+            .ok()
+            .map(|text| self.outgoing.send(Message::Text(text)).is_ok())
+            .unwrap_or(false);
+        if !sent {
+            // Nobody will ever answer this id now; don't leak its entry.
+            self.pending.by_id.lock().unwrap().remove(&id);
+        }
+
+        Box::pin(async move {
+            if !sent {
+                return Err(CdpError::internal_error());
+            }
+            let value = rx.await.map_err(|_| CdpError::internal_error())??;
+            serde_json::from_value(value).map_err(|_| CdpError::internal_error())
+        })
+    }
+
+    fn events(&self) -> Self::EventStream {
+        let (tx, rx) = mpsc::unbounded();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+}
+
+#[derive(Serialize)]
+struct CallFrame<'a, P: 'a> {
+    id: u64,
+    method: &'static str,
+    params: &'a P,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::net::TcpListener;
+
+    #[derive(Serialize)]
+    struct Echo {
+        message: String,
+    }
+
+    impl SerializeCdpCommand for Echo {
+        type Response = String;
+
+        const NAME: &'static str = "Test.echo";
+    }
+
+    #[test]
+    fn round_trips_a_command_over_a_real_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let call = match socket.read_message().unwrap() {
+                Message::Text(text) => text,
+                other => panic!("expected a text frame, got {:?}", other),
+            };
+            let call: Value = serde_json::from_str(&call).unwrap();
+            let id = call["id"].as_u64().unwrap();
+            let response = serde_json::json!({ "id": id, "result": "pong" });
+            socket
+                .write_message(Message::Text(response.to_string()))
+                .unwrap();
+        });
+
+        let client = WsClient::connect(&format!("ws://127.0.0.1:{}/", port)).unwrap();
+        let response = block_on(client.command(&Echo {
+            message: "ping".to_owned(),
+        }))
+        .unwrap();
+
+        assert_eq!(response, "pong");
+    }
+}